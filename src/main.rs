@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
 use clap::Parser;
@@ -19,6 +19,202 @@ struct Args {
     
     #[arg(short = 'v', long, help = "Print all data (not just mismatches)", default_value = "false")]
     verbose: bool,
+
+    #[arg(short = 'c', long, help = "Run a quorum-based consensus audit across node replicas instead of the archiver-vs-node comparison; loads every account into memory and does not support --snapshot/--cache-size/--output", default_value = "false")]
+    consensus: bool,
+
+    #[arg(short = 's', long, help = "Path to a snapshot file used to skip re-comparing unchanged accounts; written back after the run")]
+    snapshot: Option<PathBuf>,
+
+    #[arg(long, help = "Number of recently touched node accounts to keep cached in memory during streaming comparison", default_value = "10000")]
+    cache_size: usize,
+
+    #[arg(short = 'o', long, help = "Output format for the comparison report", value_enum, default_value = "text")]
+    output: OutputFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+    Ndjson,
+}
+
+/// A fixed-capacity least-recently-used cache, used to bound the working set of node
+/// account entries kept in memory while streaming a comparison over a large database.
+struct LruCache<K, V> {
+    capacity: usize,
+    entries: HashMap<K, V>,
+    order: VecDeque<K>,
+}
+
+impl<K: Eq + std::hash::Hash + Clone, V> LruCache<K, V> {
+    fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn touch(&mut self, key: &K) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            self.order.remove(pos);
+        }
+        self.order.push_back(key.clone());
+    }
+
+    /// Returns the cached value for `key`, computing and caching it via `f` on a miss,
+    /// evicting the least-recently-used entry first if the cache is already full.
+    fn get_or_insert_with(&mut self, key: K, f: impl FnOnce() -> V) -> &V {
+        if self.entries.contains_key(&key) {
+            self.touch(&key);
+        } else {
+            if self.entries.len() >= self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.entries.remove(&oldest);
+                }
+            }
+            let value = f();
+            self.entries.insert(key.clone(), value);
+            self.order.push_back(key.clone());
+        }
+        self.entries.get(&key).expect("just inserted or already present")
+    }
+}
+
+/// An open connection to one node's sqlite database, kept alive for the lifetime of a
+/// streaming comparison so that per-account lookups don't repeatedly reopen the file.
+struct NodeDb {
+    name: String,
+    conn: Connection,
+}
+
+fn open_node_dbs(nodes_folder: &Path) -> Result<Vec<NodeDb>> {
+    let mut dbs = Vec::new();
+
+    for entry in WalkDir::new(nodes_folder) {
+        let entry = entry?;
+        if entry.file_name() == "shardeum.sqlite" {
+            let db_path = entry.path();
+            let node_name = extract_node_name(db_path);
+
+            match Connection::open(db_path) {
+                Ok(conn) => {
+                    if let Err(e) = conn.execute(
+                        "CREATE INDEX IF NOT EXISTS idx_accounts_entry_account_id ON accountsEntry(accountId)",
+                        [],
+                    ) {
+                        eprintln!("Failed to ensure accountId index on {}: {}", db_path.display(), e);
+                    }
+                    dbs.push(NodeDb { name: node_name, conn });
+                }
+                Err(e) => {
+                    eprintln!("Failed to open node database {}: {}", db_path.display(), e);
+                }
+            }
+        }
+    }
+
+    println!("Opened {} node databases", dbs.len());
+    Ok(dbs)
+}
+
+/// Queries every node database for one account id, returning all matching entries.
+fn fetch_node_entries(dbs: &[NodeDb], account_id: &str) -> Vec<AccountEntry> {
+    let mut entries = Vec::new();
+
+    for db in dbs {
+        let mut stmt = match db.conn.prepare_cached("SELECT data FROM accountsEntry WHERE accountId = ?1") {
+            Ok(stmt) => stmt,
+            Err(e) => {
+                eprintln!("Failed to prepare node query on {}: {}", db.name, e);
+                continue;
+            }
+        };
+
+        let rows = match stmt.query_map([account_id], |row| row.get::<_, String>(0)) {
+            Ok(rows) => rows,
+            Err(e) => {
+                eprintln!("Failed to query node {} for account {}: {}", db.name, account_id, e);
+                continue;
+            }
+        };
+
+        for row in rows {
+            let data_str = match row {
+                Ok(data_str) => data_str,
+                Err(e) => {
+                    eprintln!("Failed to read row for {} in {}: {}", account_id, db.name, e);
+                    continue;
+                }
+            };
+            match serde_json::from_str::<AccountData>(&data_str) {
+                Ok(data) => {
+                    let entry = AccountEntry {
+                        account_id: account_id.to_string(),
+                        data,
+                        node_path: Some(db.name.clone()),
+                    };
+                    if entry.is_comparable() {
+                        entries.push(entry);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to parse node account data for {} in {}: {}", account_id, db.name, e);
+                }
+            }
+        }
+    }
+
+    entries
+}
+
+/// Format version written into every snapshot; a snapshot with any other version is ignored.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// On-disk baseline written after a run and consulted on the next one to short-circuit
+/// comparison for accounts whose hash hasn't changed on either side.
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    accounts: HashMap<String, SnapshotEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SnapshotEntry {
+    hash: String,
+    status: String,
+}
+
+fn load_snapshot(path: &Path) -> Result<Option<Snapshot>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let data = std::fs::read_to_string(path).context("Failed to read snapshot file")?;
+    let snapshot: Snapshot = serde_json::from_str(&data).context("Failed to parse snapshot file")?;
+
+    if snapshot.version != SNAPSHOT_VERSION {
+        eprintln!(
+            "Snapshot at {} has version {} but expected {}; ignoring stale snapshot",
+            path.display(),
+            snapshot.version,
+            SNAPSHOT_VERSION
+        );
+        return Ok(None);
+    }
+
+    Ok(Some(snapshot))
+}
+
+fn save_snapshot(path: &Path, accounts: HashMap<String, SnapshotEntry>) -> Result<()> {
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        accounts,
+    };
+    let data = serde_json::to_string_pretty(&snapshot).context("Failed to serialize snapshot")?;
+    std::fs::write(path, data).context("Failed to write snapshot file")
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -63,7 +259,7 @@ struct DataValue {
     value: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct AccountEntry {
     account_id: String,
     data: AccountData,
@@ -77,30 +273,226 @@ impl AccountEntry {
             AccountData::Special { .. } => None,
         }
     }
-    
+
     fn get_nonce(&self) -> String {
         match &self.data {
             AccountData::Regular { account, .. } => account.nonce.value.clone(),
             AccountData::Special { nonce, .. } => nonce.map_or("N/A".to_string(), |n| n.to_string()),
         }
     }
-    
+
+    fn get_code_hash(&self) -> Option<&str> {
+        match &self.data {
+            AccountData::Regular { account, .. } => Some(&account.code_hash.value),
+            AccountData::Special { .. } => None,
+        }
+    }
+
+    fn get_storage_root(&self) -> Option<&str> {
+        match &self.data {
+            AccountData::Regular { account, .. } => Some(&account.storage_root.value),
+            AccountData::Special { .. } => None,
+        }
+    }
+
+    fn get_hash(&self) -> &str {
+        match &self.data {
+            AccountData::Regular { hash, .. } => hash,
+            AccountData::Special { hash, .. } => hash,
+        }
+    }
+
     fn is_comparable(&self) -> bool {
         matches!(self.data, AccountData::Regular { .. })
     }
+
+    /// A string fingerprint of the fields that determine replica agreement:
+    /// balance, nonce, codeHash, storageRoot and the account's own hash.
+    fn fingerprint(&self) -> String {
+        format!(
+            "{}|{}|{}|{}|{}",
+            self.get_balance().unwrap_or("N/A"),
+            self.get_nonce(),
+            self.get_code_hash().unwrap_or("N/A"),
+            self.get_storage_root().unwrap_or("N/A"),
+            self.get_hash(),
+        )
+    }
+}
+
+/// Keccak-256 hash of empty code, used by EOAs. Treated as equivalent to a null/absent
+/// code hash so that EOAs with no code don't register as contract-state mismatches.
+const EMPTY_CODE_HASH: &str = "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470";
+
+/// Normalizes a raw code hash so the well-known empty-code hash reads as absent.
+fn normalize_code_hash(code_hash: Option<&str>) -> Option<String> {
+    code_hash.and_then(|h| {
+        if h.trim_start_matches("0x").eq_ignore_ascii_case(EMPTY_CODE_HASH) {
+            None
+        } else {
+            Some(h.to_string())
+        }
+    })
+}
+
+/// A per-field diff between a "pre" (archiver) and "post" (node) value.
+///
+/// `Same` carries no value since there is nothing to show the user; `Born`/`Died`
+/// carry the single value that exists on the side where the field is present.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+enum Diff<T> {
+    Same,
+    Born(T),
+    Changed(T, T),
+    Died(T),
+}
+
+impl<T: PartialEq> Diff<T> {
+    /// Classifies a field that is present on both sides: `Same` if equal, else `Changed`.
+    fn new(pre: T, post: T) -> Self {
+        if pre == post {
+            Diff::Same
+        } else {
+            Diff::Changed(pre, post)
+        }
+    }
+
+    /// Classifies a field given its optional presence on each side.
+    fn classify(pre: Option<T>, post: Option<T>) -> Self {
+        match (pre, post) {
+            (None, None) => Diff::Same,
+            (None, Some(post)) => Diff::Born(post),
+            (Some(pre), None) => Diff::Died(pre),
+            (Some(pre), Some(post)) => Diff::new(pre, post),
+        }
+    }
+
+    fn pre(&self) -> Option<&T> {
+        match self {
+            Diff::Same | Diff::Born(_) => None,
+            Diff::Changed(pre, _) | Diff::Died(pre) => Some(pre),
+        }
+    }
+
+    fn post(&self) -> Option<&T> {
+        match self {
+            Diff::Same | Diff::Died(_) => None,
+            Diff::Changed(_, post) | Diff::Born(post) => Some(post),
+        }
+    }
+
+    fn is_same(&self) -> bool {
+        matches!(self, Diff::Same)
+    }
+}
+
+/// Whether an account exists only on one side, or on both ("Alive").
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Existence {
+    Born,
+    Alive,
+    Died,
+}
+
+/// The classified diff of one account between an archiver entry and a node entry.
+#[derive(Debug, Clone, Serialize)]
+struct AccountDiff {
+    balance: Diff<String>,
+    nonce: Diff<String>,
+    code_hash: Diff<String>,
+    storage_root: Diff<String>,
+    #[serde(skip)]
+    archiver_present: bool,
+    #[serde(skip)]
+    node_present: bool,
+}
+
+impl AccountDiff {
+    fn classify(archiver_entry: Option<&AccountEntry>, node_entry: Option<&AccountEntry>) -> Self {
+        AccountDiff {
+            balance: Diff::classify(
+                archiver_entry.and_then(|e| e.get_balance()).map(String::from),
+                node_entry.and_then(|e| e.get_balance()).map(String::from),
+            ),
+            nonce: Diff::classify(
+                archiver_entry.map(|e| e.get_nonce()),
+                node_entry.map(|e| e.get_nonce()),
+            ),
+            code_hash: Diff::classify(
+                normalize_code_hash(archiver_entry.and_then(|e| e.get_code_hash())),
+                normalize_code_hash(node_entry.and_then(|e| e.get_code_hash())),
+            ),
+            storage_root: Diff::classify(
+                archiver_entry.and_then(|e| e.get_storage_root()).map(String::from),
+                node_entry.and_then(|e| e.get_storage_root()).map(String::from),
+            ),
+            archiver_present: archiver_entry.is_some(),
+            node_present: node_entry.is_some(),
+        }
+    }
+
+    /// Collapses account presence into a single Born/Alive/Died existence status.
+    ///
+    /// This is driven by whether the archiver/node entries themselves were
+    /// present, not by per-field Born/Died -- a field can legitimately go
+    /// Born/Died on its own (e.g. codeHash normalizing away to `None` on one
+    /// side) while the account is present on both sides throughout.
+    fn existence(&self) -> Existence {
+        match (self.archiver_present, self.node_present) {
+            (false, true) => Existence::Born,
+            (true, false) => Existence::Died,
+            _ => Existence::Alive,
+        }
+    }
+
+    fn is_same(&self) -> bool {
+        self.balance.is_same() && self.nonce.is_same() && self.code_hash.is_same() && self.storage_root.is_same()
+    }
+
+    fn status_label(&self) -> &'static str {
+        match self.existence() {
+            Existence::Born => "Born",
+            Existence::Died => "Died",
+            Existence::Alive if self.is_same() => "Same",
+            Existence::Alive => "Changed",
+        }
+    }
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
-    
-    let archiver_accounts = load_archiver_accounts(&args.archiver_db)
-        .context("Failed to load archiver accounts")?;
-    
-    let node_accounts = load_node_accounts(&args.nodes_folder)
-        .context("Failed to load node accounts")?;
-    
-    compare_accounts(&archiver_accounts, &node_accounts, args.verbose);
-    
+
+    if args.consensus {
+        if args.snapshot.is_some() {
+            eprintln!("Warning: --snapshot is ignored in --consensus mode (consensus mode doesn't track a baseline).");
+        }
+        if args.cache_size != 10000 {
+            eprintln!("Warning: --cache-size is ignored in --consensus mode (consensus mode loads every account into memory).");
+        }
+        if args.output != OutputFormat::Text {
+            eprintln!("Warning: --output is ignored in --consensus mode (consensus mode only prints a text report).");
+        }
+
+        // Unlike the default streaming comparison, consensus mode fully materializes every
+        // archiver and node account into memory: it needs all node replicas for an account
+        // present at once to take a majority vote, so there's no single ordered cursor to
+        // stream over. This does not scale to multi-gigabyte databases.
+        let archiver_accounts = load_archiver_accounts(&args.archiver_db)
+            .context("Failed to load archiver accounts")?;
+        let node_accounts = load_node_accounts(&args.nodes_folder)
+            .context("Failed to load node accounts")?;
+        audit_consensus(&archiver_accounts, &node_accounts);
+    } else {
+        stream_compare_accounts(
+            &args.archiver_db,
+            &args.nodes_folder,
+            args.verbose,
+            args.snapshot.as_deref(),
+            args.cache_size,
+            args.output,
+        )?;
+    }
+
     Ok(())
 }
 
@@ -222,89 +614,646 @@ fn extract_node_name(db_path: &Path) -> String {
         .to_string()
 }
 
-fn compare_accounts(
+/// For each account present on the node side, groups its node replicas by their
+/// `fingerprint`, identifies the majority group, and reports both intra-cluster and
+/// archiver-vs-majority divergence.
+fn audit_consensus(
     archiver_accounts: &HashMap<String, AccountEntry>,
     node_accounts: &HashMap<String, Vec<AccountEntry>>,
-    verbose: bool,
 ) {
-    println!("\n=== ACCOUNT COMPARISON ===\n");
-    
-    let mut mismatches = 0;
-    let mut total_comparisons = 0;
-    
-    for (account_id, archiver_entry) in archiver_accounts {
-        if let Some(node_entries) = node_accounts.get(account_id) {
-            for node_entry in node_entries {
-                total_comparisons += 1;
-                
-                let balance_match = archiver_entry.get_balance() == node_entry.get_balance();
-                let nonce_match = archiver_entry.get_nonce() == node_entry.get_nonce();
-                
-                let has_mismatch = !balance_match || !nonce_match;
-                
-                if has_mismatch {
-                    mismatches += 1;
-                }
-                
-                if verbose || has_mismatch {
-                    println!("Account ID: {}", account_id);
-                    println!("Node: {}", node_entry.node_path.as_ref().unwrap_or(&"archiver".to_string()));
-                    
-                    let arch_balance = archiver_entry.get_balance().unwrap_or("N/A");
-                    let arch_nonce = &archiver_entry.get_nonce();
-                    let node_balance = node_entry.get_balance().unwrap_or("N/A");
-                    let node_nonce = &node_entry.get_nonce();
-                    
-                    println!("  Archiver - Balance: {}, Nonce: {}", arch_balance, arch_nonce);
-                    println!("  Node     - Balance: {}, Nonce: {}", node_balance, node_nonce);
-                    
-                    if has_mismatch {
-                        println!("  STATUS: MISMATCH");
-                        if !balance_match {
-                            println!("    - Balance mismatch");
-                        }
-                        if !nonce_match {
-                            println!("    - Nonce mismatch");
-                        }
-                    } else {
-                        println!("  STATUS: MATCH");
+    println!("\n=== CONSENSUS AUDIT ===\n");
+
+    let mut audited = 0;
+    let mut node_divergences = 0;
+    let mut archiver_divergences = 0;
+
+    let mut account_ids: Vec<&String> = node_accounts.keys().collect();
+    account_ids.sort();
+
+    for account_id in account_ids {
+        let node_entries = &node_accounts[account_id];
+        if node_entries.is_empty() {
+            continue;
+        }
+        audited += 1;
+
+        let mut groups: HashMap<String, Vec<&AccountEntry>> = HashMap::new();
+        for entry in node_entries {
+            groups.entry(entry.fingerprint()).or_default().push(entry);
+        }
+
+        // HashMap iteration order is randomized per process, so a tie on group size must
+        // be broken deterministically (by fingerprint) or the reported "majority" would
+        // vary run to run on unchanged data.
+        let mut groups: Vec<(String, Vec<&AccountEntry>)> = groups.into_iter().collect();
+        groups.sort_by(|(fp_a, group_a), (fp_b, group_b)| {
+            group_b.len().cmp(&group_a.len()).then_with(|| fp_a.cmp(fp_b))
+        });
+        let (majority_fingerprint, majority_group) = groups.into_iter().next().expect("node_entries is non-empty");
+
+        let total_nodes = node_entries.len();
+        let dissenting_paths: Vec<&str> = node_entries
+            .iter()
+            .filter(|e| e.fingerprint() != majority_fingerprint)
+            .map(|e| e.node_path.as_deref().unwrap_or("unknown"))
+            .collect();
+
+        if !dissenting_paths.is_empty() {
+            node_divergences += 1;
+            println!(
+                "Account ID: {} - {}/{} nodes agree, nodes {} diverge",
+                account_id,
+                majority_group.len(),
+                total_nodes,
+                dissenting_paths.join(",")
+            );
+        }
+
+        if let Some(archiver_entry) = archiver_accounts.get(account_id) {
+            if archiver_entry.fingerprint() != majority_fingerprint {
+                archiver_divergences += 1;
+                println!(
+                    "Account ID: {} - node majority ({}/{} nodes) disagrees with archiver",
+                    account_id,
+                    majority_group.len(),
+                    total_nodes
+                );
+            }
+        }
+    }
+
+    println!("\n=== CONSENSUS SUMMARY ===");
+    println!("Accounts audited: {}", audited);
+    println!("Accounts with diverging node replicas: {}", node_divergences);
+    println!("Accounts where node majority disagrees with archiver: {}", archiver_divergences);
+}
+
+fn print_field_diff(name: &str, diff: &Diff<String>) {
+    if diff.is_same() {
+        return;
+    }
+    match (diff.pre(), diff.post()) {
+        (None, Some(post)) => println!("    {} BORN: {}", name, post),
+        (Some(pre), None) => println!("    {} DIED: {}", name, pre),
+        (Some(pre), Some(post)) => println!("    {} CHANGED: {} -> {}", name, pre, post),
+        (None, None) => {}
+    }
+}
+
+fn print_account_diff(account_id: &str, node_label: &str, diff: &AccountDiff) {
+    println!("Account ID: {}", account_id);
+    println!("Node: {}", node_label);
+    println!(
+        "  STATUS: {}",
+        match diff.existence() {
+            Existence::Born => "BORN (only in node)",
+            Existence::Died => "DIED (only in archiver)",
+            Existence::Alive if diff.is_same() => "SAME",
+            Existence::Alive => "CHANGED",
+        }
+    );
+    print_field_diff("balance", &diff.balance);
+    print_field_diff("nonce", &diff.nonce);
+    print_field_diff("codeHash", &diff.code_hash);
+    print_field_diff("storageRoot", &diff.storage_root);
+    println!();
+}
+
+/// Running tallies for a comparison pass, kept separate from the account data itself so
+/// both the main archiver-ordered stream and the node-only tail pass can share it.
+#[derive(Default)]
+struct ComparisonTally {
+    total_comparisons: u64,
+    mismatches: u64,
+    balance_mismatches: u64,
+    nonce_mismatches: u64,
+    code_hash_mismatches: u64,
+    storage_root_mismatches: u64,
+    skipped_via_snapshot: u64,
+    accounts_only_in_archiver: u64,
+    accounts_only_in_nodes: u64,
+}
+
+impl ComparisonTally {
+    fn record(&mut self, diff: &AccountDiff) -> bool {
+        self.total_comparisons += 1;
+        let has_mismatch = !diff.is_same();
+
+        if has_mismatch {
+            self.mismatches += 1;
+        }
+        if !diff.balance.is_same() {
+            self.balance_mismatches += 1;
+        }
+        if !diff.nonce.is_same() {
+            self.nonce_mismatches += 1;
+        }
+        if !diff.code_hash.is_same() {
+            self.code_hash_mismatches += 1;
+        }
+        if !diff.storage_root.is_same() {
+            self.storage_root_mismatches += 1;
+        }
+
+        has_mismatch
+    }
+
+    fn match_rate_percent(&self) -> f64 {
+        if self.total_comparisons > 0 {
+            (self.total_comparisons - self.mismatches) as f64 / self.total_comparisons as f64 * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    fn print_summary(&self) {
+        println!("=== SUMMARY ===");
+        println!("Total comparisons: {}", self.total_comparisons);
+        println!("Accounts skipped via snapshot: {}", self.skipped_via_snapshot);
+        println!("Mismatches found: {}", self.mismatches);
+        println!("  - Balance mismatches: {}", self.balance_mismatches);
+        println!("  - Nonce mismatches: {}", self.nonce_mismatches);
+        println!("  - CodeHash mismatches: {}", self.code_hash_mismatches);
+        println!("  - StorageRoot mismatches: {}", self.storage_root_mismatches);
+        println!("Accounts only in archiver: {}", self.accounts_only_in_archiver);
+        println!("Accounts only in nodes: {}", self.accounts_only_in_nodes);
+        println!("Match rate: {:.2}%", self.match_rate_percent());
+    }
+
+    fn to_summary(&self) -> ComparisonSummary {
+        ComparisonSummary {
+            total_comparisons: self.total_comparisons,
+            mismatches: self.mismatches,
+            balance_mismatches: self.balance_mismatches,
+            nonce_mismatches: self.nonce_mismatches,
+            code_hash_mismatches: self.code_hash_mismatches,
+            storage_root_mismatches: self.storage_root_mismatches,
+            skipped_via_snapshot: self.skipped_via_snapshot,
+            accounts_only_in_archiver: self.accounts_only_in_archiver,
+            accounts_only_in_nodes: self.accounts_only_in_nodes,
+            match_rate_percent: self.match_rate_percent(),
+        }
+    }
+}
+
+/// A single side's field values for one account, used by the JSON/NDJSON report.
+#[derive(Debug, Serialize)]
+struct FieldSnapshot {
+    balance: Option<String>,
+    nonce: String,
+    code_hash: Option<String>,
+    storage_root: Option<String>,
+    hash: String,
+}
+
+impl FieldSnapshot {
+    fn from_entry(entry: &AccountEntry) -> Self {
+        FieldSnapshot {
+            balance: entry.get_balance().map(String::from),
+            nonce: entry.get_nonce(),
+            code_hash: entry.get_code_hash().map(String::from),
+            storage_root: entry.get_storage_root().map(String::from),
+            hash: entry.get_hash().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct NodeComparisonRecord {
+    node_path: String,
+    fields: FieldSnapshot,
+    diff: AccountDiff,
+}
+
+/// The JSON/NDJSON report record for one account: its archiver fields (if present) and
+/// its per-node fields and diff classification against the archiver.
+#[derive(Debug, Serialize)]
+struct AccountRecord {
+    account_id: String,
+    archiver: Option<FieldSnapshot>,
+    nodes: Vec<NodeComparisonRecord>,
+}
+
+#[derive(Debug, Serialize)]
+struct ComparisonSummary {
+    total_comparisons: u64,
+    mismatches: u64,
+    balance_mismatches: u64,
+    nonce_mismatches: u64,
+    code_hash_mismatches: u64,
+    storage_root_mismatches: u64,
+    skipped_via_snapshot: u64,
+    accounts_only_in_archiver: u64,
+    accounts_only_in_nodes: u64,
+    match_rate_percent: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct ComparisonReport {
+    summary: ComparisonSummary,
+    accounts: Vec<AccountRecord>,
+}
+
+/// Emits one account's report record according to the chosen output format: printed as
+/// text immediately, printed as one NDJSON line immediately, or buffered into `records`
+/// to be wrapped into a single JSON report once the full comparison has finished.
+fn emit_account_record(
+    format: OutputFormat,
+    verbose: bool,
+    any_mismatch: bool,
+    account_id: &str,
+    archiver_entry: Option<&AccountEntry>,
+    node_diffs: &[(&AccountEntry, AccountDiff)],
+    records: &mut Vec<AccountRecord>,
+) -> Result<()> {
+    match format {
+        OutputFormat::Text => {
+            if node_diffs.is_empty() {
+                let diff = AccountDiff::classify(archiver_entry, None);
+                print_account_diff(account_id, "none", &diff);
+            } else {
+                for (node_entry, diff) in node_diffs {
+                    let has_mismatch = !diff.is_same();
+                    if verbose || has_mismatch {
+                        let node_label = node_entry.node_path.as_deref().unwrap_or("archiver");
+                        print_account_diff(account_id, node_label, diff);
                     }
-                    println!();
                 }
             }
-        } else {
-            if verbose {
-                println!("Account ID: {} (ONLY IN ARCHIVER)", account_id);
-                let arch_balance = archiver_entry.get_balance().unwrap_or("N/A");
-                let arch_nonce = &archiver_entry.get_nonce();
-                println!("  Balance: {}, Nonce: {}", arch_balance, arch_nonce);
-                println!("  STATUS: NOT FOUND IN NODES\n");
+        }
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            if !verbose && !any_mismatch {
+                return Ok(());
+            }
+            let record = AccountRecord {
+                account_id: account_id.to_string(),
+                archiver: archiver_entry.map(FieldSnapshot::from_entry),
+                nodes: node_diffs
+                    .iter()
+                    .map(|(node_entry, diff)| NodeComparisonRecord {
+                        node_path: node_entry.node_path.clone().unwrap_or_else(|| "unknown".to_string()),
+                        fields: FieldSnapshot::from_entry(node_entry),
+                        diff: diff.clone(),
+                    })
+                    .collect(),
+            };
+            match format {
+                OutputFormat::Ndjson => println!("{}", serde_json::to_string(&record)?),
+                OutputFormat::Json => records.push(record),
+                OutputFormat::Text => unreachable!(),
             }
         }
     }
-    
-    for (account_id, node_entries) in node_accounts {
-        if !archiver_accounts.contains_key(account_id) {
-            if verbose {
-                for node_entry in node_entries {
-                    println!("Account ID: {} (ONLY IN NODE: {})", account_id, 
-                            node_entry.node_path.as_ref().unwrap_or(&"unknown".to_string()));
-                    let node_balance = node_entry.get_balance().unwrap_or("N/A");
-                    let node_nonce = &node_entry.get_nonce();
-                    println!("  Balance: {}, Nonce: {}", node_balance, node_nonce);
-                    println!("  STATUS: NOT FOUND IN ARCHIVER\n");
-                }
+    Ok(())
+}
+
+/// Streams the archiver `accounts` table in account-id order, looking up each account's
+/// node-side entries on demand through a bounded LRU cache rather than preloading every
+/// account from every database into memory. A final bounded pass over each node
+/// database's account ids surfaces accounts that exist only on the node side.
+fn stream_compare_accounts(
+    archiver_db: &Path,
+    nodes_folder: &Path,
+    verbose: bool,
+    snapshot_path: Option<&Path>,
+    cache_size: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    if format == OutputFormat::Text {
+        println!("\n=== ACCOUNT COMPARISON ===\n");
+    }
+
+    let node_dbs = open_node_dbs(nodes_folder)?;
+    let mut cache: LruCache<String, Vec<AccountEntry>> = LruCache::new(cache_size);
+
+    let previous_snapshot = snapshot_path.and_then(|path| match load_snapshot(path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("Failed to load snapshot: {:#}", e);
+            None
+        }
+    });
+
+    let mut tally = ComparisonTally::default();
+    let mut next_snapshot: HashMap<String, SnapshotEntry> = HashMap::new();
+    let mut seen_ids: HashSet<String> = HashSet::new();
+    let mut records: Vec<AccountRecord> = Vec::new();
+
+    let conn = Connection::open(archiver_db).context("Failed to open archiver database")?;
+    let mut stmt = conn
+        .prepare("SELECT accountId, data FROM accounts ORDER BY accountId")
+        .context("Failed to prepare archiver query")?;
+    let rows = stmt.query_map([], |row| {
+        let account_id: String = row.get(0)?;
+        let data_str: String = row.get(1)?;
+        Ok((account_id, data_str))
+    })?;
+
+    for row in rows {
+        let (account_id, data_str) = row?;
+        let data = match serde_json::from_str::<AccountData>(&data_str) {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("Failed to parse archiver account data for {}: {}", account_id, e);
+                continue;
+            }
+        };
+        let archiver_entry = AccountEntry { account_id: account_id.clone(), data, node_path: None };
+        if !archiver_entry.is_comparable() {
+            continue;
+        }
+        seen_ids.insert(account_id.clone());
+
+        let archiver_hash = archiver_entry.get_hash();
+        let stored = previous_snapshot.as_ref().and_then(|s| s.accounts.get(&account_id)).cloned();
+        let node_entries =
+            cache.get_or_insert_with(account_id.clone(), || fetch_node_entries(&node_dbs, &account_id)).clone();
+
+        let unchanged = stored.as_ref().is_some_and(|stored| {
+            stored.hash == archiver_hash
+                && !node_entries.is_empty()
+                && node_entries.iter().all(|e| e.get_hash() == stored.hash)
+        });
+
+        if unchanged {
+            tally.skipped_via_snapshot += 1;
+            next_snapshot.insert(account_id.clone(), stored.expect("unchanged implies stored"));
+            continue;
+        }
+
+        if node_entries.is_empty() {
+            tally.accounts_only_in_archiver += 1;
+            let diff = AccountDiff::classify(Some(&archiver_entry), None);
+            emit_account_record(format, verbose, true, &account_id, Some(&archiver_entry), &[], &mut records)?;
+            next_snapshot.insert(
+                account_id.clone(),
+                SnapshotEntry { hash: archiver_hash.to_string(), status: diff.status_label().to_string() },
+            );
+            continue;
+        }
+
+        let mut any_mismatch = false;
+        let node_diffs: Vec<(&AccountEntry, AccountDiff)> = node_entries
+            .iter()
+            .map(|node_entry| {
+                let diff = AccountDiff::classify(Some(&archiver_entry), Some(node_entry));
+                any_mismatch |= tally.record(&diff);
+                next_snapshot.insert(
+                    account_id.clone(),
+                    SnapshotEntry { hash: archiver_hash.to_string(), status: diff.status_label().to_string() },
+                );
+                (node_entry, diff)
+            })
+            .collect();
+
+        emit_account_record(format, verbose, any_mismatch, &account_id, Some(&archiver_entry), &node_diffs, &mut records)?;
+    }
+
+    for db in &node_dbs {
+        let mut stmt = db
+            .conn
+            .prepare("SELECT DISTINCT accountId FROM accountsEntry")
+            .context("Failed to prepare node account id query")?;
+        let ids = stmt.query_map([], |row| row.get::<_, String>(0))?;
+
+        for id in ids {
+            let id = id?;
+            if !seen_ids.insert(id.clone()) {
+                continue;
             }
+
+            let node_entries = cache.get_or_insert_with(id.clone(), || fetch_node_entries(&node_dbs, &id)).clone();
+            if node_entries.is_empty() {
+                continue;
+            }
+            tally.accounts_only_in_nodes += 1;
+
+            let node_diffs: Vec<(&AccountEntry, AccountDiff)> = node_entries
+                .iter()
+                .map(|node_entry| {
+                    let diff = AccountDiff::classify(None, Some(node_entry));
+                    next_snapshot.insert(
+                        id.clone(),
+                        SnapshotEntry { hash: node_entry.get_hash().to_string(), status: diff.status_label().to_string() },
+                    );
+                    (node_entry, diff)
+                })
+                .collect();
+
+            emit_account_record(format, verbose, true, &id, None, &node_diffs, &mut records)?;
         }
     }
-    
-    println!("=== SUMMARY ===");
-    println!("Total comparisons: {}", total_comparisons);
-    println!("Mismatches found: {}", mismatches);
-    println!("Match rate: {:.2}%", 
-            if total_comparisons > 0 { 
-                (total_comparisons - mismatches) as f64 / total_comparisons as f64 * 100.0 
-            } else { 
-                0.0 
-            });
+
+    if let Some(path) = snapshot_path {
+        if let Err(e) = save_snapshot(path, next_snapshot) {
+            eprintln!("Failed to write snapshot: {:#}", e);
+        }
+    }
+
+    match format {
+        OutputFormat::Text => tally.print_summary(),
+        OutputFormat::Ndjson => println!("{}", serde_json::to_string(&tally.to_summary())?),
+        OutputFormat::Json => {
+            let report = ComparisonReport { summary: tally.to_summary(), accounts: records };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod lru_cache_tests {
+    use super::*;
+
+    #[test]
+    fn caches_repeated_lookups_without_recomputing() {
+        let mut cache: LruCache<String, u32> = LruCache::new(2);
+        let mut calls = 0;
+        cache.get_or_insert_with("a".to_string(), || {
+            calls += 1;
+            1
+        });
+        cache.get_or_insert_with("a".to_string(), || {
+            calls += 1;
+            1
+        });
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entry_when_full() {
+        let mut cache: LruCache<String, u32> = LruCache::new(2);
+        cache.get_or_insert_with("a".to_string(), || 1);
+        cache.get_or_insert_with("b".to_string(), || 2);
+        // Touch "a" so "b" becomes the least-recently-used entry.
+        cache.get_or_insert_with("a".to_string(), || 1);
+        cache.get_or_insert_with("c".to_string(), || 3);
+
+        let mut a_recomputed = false;
+        cache.get_or_insert_with("a".to_string(), || {
+            a_recomputed = true;
+            1
+        });
+        assert!(!a_recomputed, "\"a\" was touched most recently and should still be cached");
+
+        let mut b_recomputed = false;
+        cache.get_or_insert_with("b".to_string(), || {
+            b_recomputed = true;
+            2
+        });
+        assert!(b_recomputed, "\"b\" should have been evicted to make room for \"c\"");
+    }
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use super::*;
+
+    #[test]
+    fn new_is_same_when_equal_else_changed() {
+        assert_eq!(Diff::new(1, 1), Diff::Same);
+        assert_eq!(Diff::new(1, 2), Diff::Changed(1, 2));
+    }
+
+    #[test]
+    fn classify_handles_one_sided_presence() {
+        assert_eq!(Diff::classify(None::<i32>, None), Diff::Same);
+        assert_eq!(Diff::classify(None, Some(1)), Diff::Born(1));
+        assert_eq!(Diff::classify(Some(1), None), Diff::Died(1));
+        assert_eq!(Diff::classify(Some(1), Some(1)), Diff::Same);
+        assert_eq!(Diff::classify(Some(1), Some(2)), Diff::Changed(1, 2));
+    }
+
+    #[test]
+    fn pre_and_post_match_each_variant() {
+        let same: Diff<i32> = Diff::Same;
+        assert_eq!(same.pre(), None);
+        assert_eq!(same.post(), None);
+
+        let born = Diff::Born(2);
+        assert_eq!(born.pre(), None);
+        assert_eq!(born.post(), Some(&2));
+
+        let died = Diff::Died(1);
+        assert_eq!(died.pre(), Some(&1));
+        assert_eq!(died.post(), None);
+
+        let changed = Diff::Changed(1, 2);
+        assert_eq!(changed.pre(), Some(&1));
+        assert_eq!(changed.post(), Some(&2));
+    }
+}
+
+#[cfg(test)]
+mod account_diff_tests {
+    use super::*;
+
+    #[test]
+    fn existence_is_alive_when_a_field_goes_born_or_died_on_both_present_accounts() {
+        // codeHash normalizing empty-hash -> None on one side and a real hash on the
+        // other produces a per-field Born/Died, even though the account itself is
+        // present on both sides throughout (e.g. an EOA that deploys a contract).
+        let diff = AccountDiff {
+            balance: Diff::Same,
+            nonce: Diff::Same,
+            code_hash: Diff::classify(None, Some("deadbeef".to_string())),
+            storage_root: Diff::Same,
+            archiver_present: true,
+            node_present: true,
+        };
+        assert_eq!(diff.existence(), Existence::Alive);
+        assert_eq!(diff.status_label(), "Changed");
+    }
+
+    #[test]
+    fn existence_tracks_account_presence_not_per_field_presence() {
+        let born = AccountDiff::classify(None, Some(&account_entry("1")));
+        assert_eq!(born.existence(), Existence::Born);
+
+        let died = AccountDiff::classify(Some(&account_entry("1")), None);
+        assert_eq!(died.existence(), Existence::Died);
+
+        let alive = AccountDiff::classify(Some(&account_entry("1")), Some(&account_entry("1")));
+        assert_eq!(alive.existence(), Existence::Alive);
+    }
+
+    fn account_entry(balance: &str) -> AccountEntry {
+        AccountEntry {
+            account_id: "acct".to_string(),
+            data: AccountData::Regular {
+                account: Account {
+                    balance: DataValue { data_type: "BigInt".to_string(), value: balance.to_string() },
+                    code_hash: DataValue { data_type: "String".to_string(), value: EMPTY_CODE_HASH.to_string() },
+                    nonce: DataValue { data_type: "BigInt".to_string(), value: "0".to_string() },
+                    storage_root: DataValue { data_type: "String".to_string(), value: EMPTY_CODE_HASH.to_string() },
+                },
+                account_type: 0,
+                eth_address: None,
+                hash: "hash".to_string(),
+                timestamp: 0,
+            },
+            node_path: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod normalize_code_hash_tests {
+    use super::*;
+
+    #[test]
+    fn empty_code_hash_normalizes_to_none_regardless_of_case_or_prefix() {
+        assert_eq!(normalize_code_hash(Some(EMPTY_CODE_HASH)), None);
+        assert_eq!(normalize_code_hash(Some(&format!("0x{}", EMPTY_CODE_HASH))), None);
+        assert_eq!(normalize_code_hash(Some(&EMPTY_CODE_HASH.to_uppercase())), None);
+    }
+
+    #[test]
+    fn other_hashes_pass_through_unchanged() {
+        assert_eq!(normalize_code_hash(None), None);
+        assert_eq!(normalize_code_hash(Some("deadbeef")), Some("deadbeef".to_string()));
+    }
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+    use super::*;
+
+    fn temp_snapshot_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("account_db_compare_test_{}_{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn stale_version_is_rejected() {
+        let path = temp_snapshot_path("stale_version");
+        let stale = Snapshot { version: SNAPSHOT_VERSION + 1, accounts: HashMap::new() };
+        std::fs::write(&path, serde_json::to_string(&stale).unwrap()).unwrap();
+
+        let loaded = load_snapshot(&path).unwrap();
+        assert!(loaded.is_none(), "a snapshot with a newer version should be ignored, not trusted");
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn missing_file_returns_none() {
+        let path = temp_snapshot_path("missing");
+        std::fs::remove_file(&path).ok();
+        assert!(load_snapshot(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn round_trips_through_save_and_load() {
+        let path = temp_snapshot_path("round_trip");
+        let mut accounts = HashMap::new();
+        accounts.insert("acct1".to_string(), SnapshotEntry { hash: "h1".to_string(), status: "Same".to_string() });
+        save_snapshot(&path, accounts.clone()).unwrap();
+
+        let loaded = load_snapshot(&path).unwrap().expect("just-saved snapshot should load");
+        assert_eq!(loaded.version, SNAPSHOT_VERSION);
+        assert_eq!(loaded.accounts, accounts);
+
+        std::fs::remove_file(&path).ok();
+    }
 }
\ No newline at end of file